@@ -50,24 +50,74 @@ extern crate srml_balances as balances;
 use rstd::prelude::*;
 use rstd::marker::PhantomData;
 use rstd::result;
-use primitives::traits::{self, Header, Zero, One, Checkable, Applyable, CheckEqual, OnFinalise,
-	MakePayment, Hash};
+use primitives::traits::{self, Header, Zero, One, SimpleArithmetic, Checkable, Applyable, CheckEqual,
+	OnFinalise, OnInitialise, OnRuntimeUpgrade, OffchainWorker, MakePayment, Hash, CurrentHeight,
+	BlockNumberToHash, Get};
 use runtime_support::Dispatchable;
 use codec::{Codec, Encode};
 use system::extrinsics_root;
-use primitives::{ApplyOutcome, ApplyError};
+use primitives::{TransactionValidity, TransactionPriority, TransactionLongevity, RuntimeVersion};
+
+/// The outcome of dispatching an applied extrinsic.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum ApplyOutcome {
+	/// Successful application (implies dispatch was successful too).
+	Success,
+	/// Failed to dispatch, giving the reason the dispatched call returned.
+	Fail(&'static str),
+}
+
+/// The error type used when an extrinsic can't be applied at all, i.e. it should never have been
+/// included in the block.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum ApplyError {
+	/// The extrinsic failed `check_with`: its signature (or signed payload) is invalid. Every
+	/// `check_with` error is reported here, including a mortal extrinsic rejected for its era.
+	BadSignature(&'static str),
+	/// This extrinsic's index is from the past; it has already happened.
+	Stale,
+	/// This extrinsic's index is from the future; it isn't yet valid.
+	Future,
+	/// The sender doesn't have enough funds to pay for the transaction.
+	CantPay,
+}
+
+/// The result of applying an extrinsic.
+pub type ApplyResult = result::Result<ApplyOutcome, ApplyError>;
+
+/// Implementation of the `Checkable` context used by `Executive` to check extrinsics: resolves
+/// addresses via `Lookup` and answers the chain-state queries (current height, block hash of an
+/// era's anchor block) needed to validate a mortal extrinsic's signature and liveness.
+pub struct ChainContext<System, Lookup>(PhantomData<(System, Lookup)>);
+
+impl<System, Lookup> Default for ChainContext<System, Lookup> {
+	fn default() -> Self {
+		ChainContext(PhantomData)
+	}
+}
 
-mod internal {
-	pub enum ApplyError {
-		BadSignature(&'static str),
-		Stale,
-		Future,
-		CantPay,
+impl<System: system::Trait, Lookup: traits::Lookup<Target=System::AccountId>> traits::Lookup
+	for ChainContext<System, Lookup>
+{
+	type Source = Lookup::Source;
+	type Target = System::AccountId;
+	fn lookup(s: Self::Source) -> Result<Self::Target, &'static str> {
+		Lookup::lookup(s)
 	}
+}
 
-	pub enum ApplyOutcome {
-		Success,
-		Fail(&'static str),
+impl<System: system::Trait, Lookup> CurrentHeight for ChainContext<System, Lookup> {
+	type BlockNumber = System::BlockNumber;
+	fn current_height(&self) -> Self::BlockNumber {
+		<system::Module<System>>::block_number()
+	}
+}
+
+impl<System: system::Trait, Lookup> BlockNumberToHash for ChainContext<System, Lookup> {
+	type BlockNumber = System::BlockNumber;
+	type Hash = System::Hash;
+	fn block_number_to_hash(&self, n: Self::BlockNumber) -> Option<Self::Hash> {
+		Some(<system::Module<System>>::block_hash(n))
 	}
 }
 
@@ -76,8 +126,18 @@ pub struct Executive<
 	Block,
 	Lookup,
 	Payment,
+	Initialisation,
 	Finalisation,
->(PhantomData<(System, Block, Lookup, Payment, Finalisation)>);
+	Migration,
+	Offchain,
+	Version,
+>(PhantomData<(System, Block, Lookup, Payment, Initialisation, Finalisation, Migration, Offchain, Version)>);
+
+/// The storage key `perform_runtime_upgrade_if_needed` uses to remember the spec_version of the
+/// runtime that last ran a migration. This lives entirely in `executive`'s own keyspace rather
+/// than in `system`'s, so the feature has no dependency on a `system` storage item that does not
+/// exist.
+const LAST_RUNTIME_UPGRADE_KEY: &[u8] = b":executive:last_runtime_upgrade";
 
 impl<
 	Address,
@@ -85,16 +145,48 @@ impl<
 	Block: traits::Block<Header=System::Header, Hash=System::Hash>,
 	Lookup: traits::Lookup<Source=Address, Target=System::AccountId>,
 	Payment: MakePayment<System::AccountId>,
+	Initialisation: OnInitialise<System::BlockNumber>,
 	Finalisation: OnFinalise<System::BlockNumber>,
-> Executive<System, Block, Lookup, Payment, Finalisation> where
-	Block::Extrinsic: Checkable<fn(Address) -> Result<System::AccountId, &'static str>> + Codec,
-	<Block::Extrinsic as Checkable<fn(Address) -> Result<System::AccountId, &'static str>>>::Checked: Applyable<Index=System::Index, AccountId=System::AccountId>,
-	<<Block::Extrinsic as Checkable<fn(Address) -> Result<System::AccountId, &'static str>>>::Checked as Applyable>::Call: Dispatchable,
-	<<<Block::Extrinsic as Checkable<fn(Address) -> Result<System::AccountId, &'static str>>>::Checked as Applyable>::Call as Dispatchable>::Origin: From<Option<System::AccountId>>
+	Migration: OnRuntimeUpgrade,
+	Offchain: OffchainWorker<System::BlockNumber>,
+	Version: Get<RuntimeVersion>,
+> Executive<System, Block, Lookup, Payment, Initialisation, Finalisation, Migration, Offchain, Version> where
+	System::Index: SimpleArithmetic,
+	Block::Extrinsic: Checkable<ChainContext<System, Lookup>> + Codec,
+	<Block::Extrinsic as Checkable<ChainContext<System, Lookup>>>::Checked: Applyable<Index=System::Index, AccountId=System::AccountId>,
+	<<Block::Extrinsic as Checkable<ChainContext<System, Lookup>>>::Checked as Applyable>::Call: Dispatchable,
+	<<<Block::Extrinsic as Checkable<ChainContext<System, Lookup>>>::Checked as Applyable>::Call as Dispatchable>::Origin: From<Option<System::AccountId>>
 {
 	/// Start the execution of a particular block.
+	///
+	/// The runtime upgrade check runs after `system::initialise` (so it has a block number and
+	/// parent hash to work with) but before `Initialisation::on_initialise` (so that, when a
+	/// migration does run, every module's `on_initialise` executes against the post-migration
+	/// storage layout rather than the stale one).
 	pub fn initialise_block(header: &System::Header) {
 		<system::Module<System>>::initialise(header.number(), header.parent_hash(), header.extrinsics_root());
+		Self::perform_runtime_upgrade_if_needed();
+		Initialisation::on_initialise(header.number().clone());
+	}
+
+	/// Compare the runtime's compiled-in version (`Version`, the same value `Core_version`
+	/// reports) against the version recorded on-chain and, if they differ, run the one-off
+	/// migration hook and persist the new version. This must happen before any extrinsic in the
+	/// block is processed.
+	///
+	/// `Version` is a generic parameter of `Executive` itself (like `Migration`/`Offchain`),
+	/// not an associated type on `system::Trait`: it's runtime-version metadata, not a
+	/// migration-hook concern, and threading it this way needs no change to `system` at all.
+	/// Likewise the recorded on-chain version lives in `executive`'s own storage (see
+	/// `LAST_RUNTIME_UPGRADE_KEY`) rather than in a `system` storage item this series doesn't
+	/// establish.
+	fn perform_runtime_upgrade_if_needed() {
+		let current_version = Version::get();
+		let last: Option<RuntimeVersion> = runtime_support::storage::unhashed::get(LAST_RUNTIME_UPGRADE_KEY);
+		if last.as_ref().map(|v| v.spec_version) != Some(current_version.spec_version) {
+			Migration::on_runtime_upgrade();
+			runtime_support::storage::unhashed::put(LAST_RUNTIME_UPGRADE_KEY, &current_version);
+		}
 	}
 
 	fn initial_checks(block: &Block) {
@@ -143,20 +235,89 @@ impl<
 		<system::Module<System>>::finalise()
 	}
 
+	/// Run the off-chain worker for the just-imported block `header`.
+	///
+	/// This re-establishes the block context as `initialise_block` would, without executing any
+	/// of the block's extrinsics, then hands off to the runtime's off-chain worker hook. Anything
+	/// the hook does (HTTP fetches, local computation, submitting new transactions) happens
+	/// outside of consensus and has no bearing on the state root checked by `final_checks`.
+	pub fn offchain_worker(header: &System::Header) {
+		Self::initialise_block(header);
+		Offchain::offchain_worker(header.number().clone());
+	}
+
 	/// Apply extrinsic outside of the block execution function.
 	/// This doesn't attempt to validate anything regarding the block, but it builds a list of uxt
 	/// hashes.
-	pub fn apply_extrinsic(uxt: Block::Extrinsic) -> result::Result<ApplyOutcome, ApplyError> {
+	pub fn apply_extrinsic(uxt: Block::Extrinsic) -> ApplyResult {
 		let encoded = uxt.encode();
 		let encoded_len = encoded.len();
 		<system::Module<System>>::note_extrinsic(encoded);
-		match Self::apply_extrinsic_no_note_with_len(uxt, encoded_len) {
-			Ok(internal::ApplyOutcome::Success) => Ok(ApplyOutcome::Success),
-			Ok(internal::ApplyOutcome::Fail(_)) => Ok(ApplyOutcome::Fail),
-			Err(internal::ApplyError::CantPay) => Err(ApplyError::CantPay),
-			Err(internal::ApplyError::BadSignature(_)) => Err(ApplyError::BadSignature),
-			Err(internal::ApplyError::Stale) => Err(ApplyError::Stale),
-			Err(internal::ApplyError::Future) => Err(ApplyError::Future),
+		Self::apply_extrinsic_no_note_with_len(uxt, encoded_len)
+	}
+
+	/// Check a given signed transaction for validity: its signature, the sender's nonce and
+	/// whether the sender can pay the fee. The call is not dispatched and the nonce is not
+	/// incremented. A transaction whose nonce is ahead of the sender's current one is valid and
+	/// `requires` the tag of the nonce before it.
+	///
+	/// `MakePayment` exposes no non-mutating "would this payment succeed" query, only
+	/// `make_payment` itself, which debits the sender; there is no other way here to check
+	/// affordability. Because of that, this function DOES mutate storage (the nonce is not
+	/// touched, but the fee debit is), and it is the caller's (the transaction pool's)
+	/// responsibility to run it against a discardable overlay and throw that overlay away
+	/// afterwards — never against the real block-building storage.
+	pub fn validate_transaction(uxt: Block::Extrinsic) -> TransactionValidity {
+		// Note errors > 0 are from ApplyError
+		const MISSING_SENDER: i8 = -1;
+
+		let encoded_len = uxt.encode().len();
+
+		// `check_with` is handed `current_height()` (via `ChainContext`) precisely so it can
+		// reject a mortal extrinsic whose era has died, in addition to verifying the signature
+		// against the anchor hash; see `apply_extrinsic_no_note_with_len` for the full rationale.
+		// Anchor-hash resolution only bounds how far back a birth block can be referenced (the
+		// system's block-hash retention window) — it is `check_with`'s own era/period comparison
+		// against `current_height()`, not retention, that enforces the death bound.
+		let xt = match uxt.check_with(&ChainContext::default()) {
+			Ok(xt) => xt,
+			Err(_) => return TransactionValidity::Invalid(primitives::ApplyError::BadSignature as i8),
+		};
+
+		let sender = match xt.sender() {
+			Some(sender) => sender,
+			None => return TransactionValidity::Invalid(MISSING_SENDER),
+		};
+
+		let expected_index = <system::Module<System>>::account_nonce(sender);
+		if xt.index() < &expected_index {
+			return TransactionValidity::Invalid(primitives::ApplyError::Stale as i8);
+		}
+
+		if Payment::make_payment(sender, encoded_len).is_err() {
+			return TransactionValidity::Invalid(primitives::ApplyError::CantPay as i8);
+		}
+
+		let index = *xt.index();
+		let provides = vec![(sender, index).encode()];
+		let requires = if index == expected_index {
+			Vec::new()
+		} else {
+			vec![(sender, index - One::one()).encode()]
+		};
+
+		// `MakePayment` only reports whether the fee was paid, not the amount charged, so the
+		// encoded length is used as the fee proxy: priority falls as the encoded length grows.
+		// It is not computed from the amount `make_payment` charged, so two extrinsics of the
+		// same length get the same priority whatever fee schedule `Payment` applies; `.max(1)`
+		// only guards the division against a degenerate zero-length extrinsic.
+		let fee_weight = encoded_len.max(1) as TransactionPriority;
+
+		TransactionValidity::Valid {
+			priority: TransactionPriority::max_value() / fee_weight,
+			requires,
+			provides,
+			longevity: TransactionLongevity::max_value(),
 		}
 	}
 
@@ -164,28 +325,34 @@ impl<
 	fn apply_extrinsic_no_note(uxt: Block::Extrinsic) {
 		let l = uxt.encode().len();
 		match Self::apply_extrinsic_no_note_with_len(uxt, l) {
-			Ok(internal::ApplyOutcome::Success) => (),
-			Ok(internal::ApplyOutcome::Fail(e)) => runtime_io::print(e),
-			Err(internal::ApplyError::CantPay) => panic!("All extrinsics should have sender able to pay their fees"),
-			Err(internal::ApplyError::BadSignature(_)) => panic!("All extrinsics should be properly signed"),
-			Err(internal::ApplyError::Stale) | Err(internal::ApplyError::Future) => panic!("All extrinsics should have the correct nonce"),
+			Ok(ApplyOutcome::Success) => (),
+			Ok(ApplyOutcome::Fail(e)) => runtime_io::print(e),
+			Err(ApplyError::CantPay) => panic!("All extrinsics should have sender able to pay their fees"),
+			Err(ApplyError::BadSignature(_)) => panic!("All extrinsics should be properly signed"),
+			Err(ApplyError::Stale) | Err(ApplyError::Future) =>
+				panic!("All extrinsics should have the correct nonce"),
 		}
 	}
 
 	/// Actually apply an extrinsic given its `encoded_len`; this doesn't note its hash.
-	fn apply_extrinsic_no_note_with_len(uxt: Block::Extrinsic, encoded_len: usize) -> result::Result<internal::ApplyOutcome, internal::ApplyError> {
-		// Verify the signature is good.
-		let xt = uxt.check_with(Lookup::lookup).map_err(internal::ApplyError::BadSignature)?;
+	fn apply_extrinsic_no_note_with_len(uxt: Block::Extrinsic, encoded_len: usize) -> result::Result<ApplyOutcome, ApplyError> {
+		// Verify the signature is good. `ChainContext` gives `check_with` both the anchor hash
+		// (via `system::block_hash`) and `current_height()`, and it is `check_with` itself —
+		// not anything here — that rejects a mortal extrinsic whose era has died, by comparing
+		// the era's `period`/phase against `current_height()`. The anchor-hash lookup only
+		// bounds how far back a birth block can be referenced (the retention window); it does
+		// not by itself enforce the death bound.
+		let xt = uxt.check_with(&ChainContext::default()).map_err(ApplyError::BadSignature)?;
 
 		if let Some(sender) = xt.sender() {
 			// check index
 			let expected_index = <system::Module<System>>::account_nonce(sender);
 			if xt.index() != &expected_index { return Err(
-				if xt.index() < &expected_index { internal::ApplyError::Stale } else { internal::ApplyError::Future }
+				if xt.index() < &expected_index { ApplyError::Stale } else { ApplyError::Future }
 			) }
 
 			// pay any fees.
-			Payment::make_payment(sender, encoded_len).map_err(|_| internal::ApplyError::CantPay)?;
+			Payment::make_payment(sender, encoded_len).map_err(|_| ApplyError::CantPay)?;
 
 			// AUDIT: Under no circumstances may this function panic from here onwards.
 
@@ -198,7 +365,7 @@ impl<
 		let r = f.dispatch(s.into());
 		<system::Module<System>>::note_applied_extrinsic(&r);
 
-		r.map(|_| internal::ApplyOutcome::Success).or_else(|e| Ok(internal::ApplyOutcome::Fail(e)))
+		r.map(|_| ApplyOutcome::Success).or_else(|e| Ok(ApplyOutcome::Fail(e)))
 	}
 
 	fn final_checks(header: &System::Header) {
@@ -235,6 +402,58 @@ mod tests {
 		}
 	}
 
+	thread_local! {
+		// The runtime's compiled-in spec_version, as reported through `TestVersion`. Tests that
+		// exercise the upgrade hook bump this to simulate a new runtime being compiled in.
+		static TEST_SPEC_VERSION: ::std::cell::Cell<u32> = ::std::cell::Cell::new(1);
+		static MIGRATIONS_RUN: ::std::cell::Cell<u32> = ::std::cell::Cell::new(0);
+	}
+
+	pub struct TestVersion;
+	impl Get<RuntimeVersion> for TestVersion {
+		fn get() -> RuntimeVersion {
+			RuntimeVersion {
+				spec_name: "test".into(),
+				impl_name: "test".into(),
+				authoring_version: 1,
+				spec_version: TEST_SPEC_VERSION.with(|v| v.get()),
+				impl_version: 1,
+				apis: Default::default(),
+			}
+		}
+	}
+
+	pub struct CountingMigration;
+	impl OnRuntimeUpgrade for CountingMigration {
+		fn on_runtime_upgrade() {
+			MIGRATIONS_RUN.with(|m| m.set(m.get() + 1));
+			HOOK_ORDER.with(|o| o.borrow_mut().push("migration"));
+		}
+	}
+
+	thread_local! {
+		static HOOK_ORDER: ::std::cell::RefCell<Vec<&'static str>> = ::std::cell::RefCell::new(Vec::new());
+	}
+
+	pub struct LoggingInitialisation;
+	impl OnInitialise<u64> for LoggingInitialisation {
+		fn on_initialise(_n: u64) {
+			HOOK_ORDER.with(|o| o.borrow_mut().push("on_initialise"));
+		}
+	}
+
+	pub struct LoggingOffchainWorker;
+	impl OffchainWorker<u64> for LoggingOffchainWorker {
+		fn offchain_worker(n: u64) {
+			HOOK_ORDER.with(|o| o.borrow_mut().push("offchain"));
+			OFFCHAIN_WORKER_BLOCK.with(|b| b.set(n));
+		}
+	}
+
+	thread_local! {
+		static OFFCHAIN_WORKER_BLOCK: ::std::cell::Cell<u64> = ::std::cell::Cell::new(0);
+	}
+
 	impl_outer_origin! {
 		pub enum Origin for Runtime {
 		}
@@ -269,7 +488,20 @@ mod tests {
 	}
 
 	type TestXt = primitives::testing::TestXt<Call<Runtime>>;
-	type Executive = super::Executive<Runtime, Block<TestXt>, NullLookup, balances::Module<Runtime>, ()>;
+	type Executive = super::Executive<
+		Runtime, Block<TestXt>, NullLookup, balances::Module<Runtime>, (), (), (), (), TestVersion
+	>;
+	type ExecutiveWithMigration = super::Executive<
+		Runtime, Block<TestXt>, NullLookup, balances::Module<Runtime>, (), (), CountingMigration, (), TestVersion
+	>;
+	type ExecutiveWithOrderLog = super::Executive<
+		Runtime, Block<TestXt>, NullLookup, balances::Module<Runtime>,
+		LoggingInitialisation, (), CountingMigration, (), TestVersion
+	>;
+	type ExecutiveWithOffchainWorker = super::Executive<
+		Runtime, Block<TestXt>, NullLookup, balances::Module<Runtime>,
+		LoggingInitialisation, (), (), LoggingOffchainWorker, TestVersion
+	>;
 
 	#[test]
 	fn balance_transfer_dispatch_works() {
@@ -293,6 +525,157 @@ mod tests {
 		});
 	}
 
+	/// A test externality funding account 1 with 111 units, under the same fee schedule
+	/// `balance_transfer_dispatch_works` uses, for tests that need a funded sender without
+	/// caring about the exact fee parameters.
+	fn new_funded_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
+		let mut t = system::GenesisConfig::<Runtime>::default().build_storage().unwrap();
+		t.extend(balances::GenesisConfig::<Runtime> {
+			balances: vec![(1, 111)],
+			transaction_base_fee: 10,
+			transaction_byte_fee: 0,
+			existential_deposit: 0,
+			transfer_fee: 0,
+			creation_fee: 0,
+			reclaim_rebate: 0,
+		}.build_storage().unwrap());
+		t.into()
+	}
+
+	#[test]
+	fn failed_dispatch_is_reported_as_fail_outcome() {
+		// Sender only has 111, so this transfer should fail to dispatch rather than panic.
+		let xt = primitives::testing::TestXt(Some(1), 0, Call::transfer(2.into(), 1_000));
+		let mut t = new_funded_test_ext();
+		with_externalities(&mut t, || {
+			Executive::initialise_block(&Header::new(1, H256::default(), H256::default(), [69u8; 32].into(), Digest::default()));
+			match Executive::apply_extrinsic(xt) {
+				Ok(ApplyOutcome::Fail(_)) => (),
+				other => panic!("expected Ok(ApplyOutcome::Fail(_)), got {:?}", other),
+			}
+		});
+	}
+
+	#[test]
+	fn validate_transaction_tags_and_errors() {
+		let mut t = new_funded_test_ext();
+		with_externalities(&mut t, || {
+			Executive::initialise_block(&Header::new(1, H256::default(), H256::default(), [69u8; 32].into(), Digest::default()));
+
+			// Bump account 1's nonce to 1 so nonce 0 becomes stale below.
+			let first = primitives::testing::TestXt(Some(1), 0, Call::transfer(2.into(), 1));
+			assert_eq!(Executive::apply_extrinsic(first), Ok(ApplyOutcome::Success));
+
+			match Executive::validate_transaction(primitives::testing::TestXt(Some(1), 0, Call::transfer(2.into(), 1))) {
+				TransactionValidity::Invalid(e) => assert_eq!(e, primitives::ApplyError::Stale as i8),
+				_ => panic!("stale nonce should be rejected as Invalid(Stale)"),
+			}
+
+			match Executive::validate_transaction(primitives::testing::TestXt(Some(1), 1, Call::transfer(2.into(), 1))) {
+				TransactionValidity::Valid { requires, provides, .. } => {
+					assert!(requires.is_empty());
+					assert_eq!(provides, vec![(1u64, 1u64).encode()]);
+				}
+				_ => panic!("expected nonce should provide its own tag with no requirement"),
+			}
+
+			match Executive::validate_transaction(primitives::testing::TestXt(Some(1), 3, Call::transfer(2.into(), 1))) {
+				TransactionValidity::Valid { requires, provides, .. } => {
+					assert_eq!(requires, vec![(1u64, 2u64).encode()]);
+					assert_eq!(provides, vec![(1u64, 3u64).encode()]);
+				}
+				_ => panic!("future nonce should require the preceding nonce's tag"),
+			}
+
+			match Executive::validate_transaction(primitives::testing::TestXt(Some(2), 0, Call::transfer(1.into(), 1))) {
+				TransactionValidity::Invalid(e) => assert_eq!(e, primitives::ApplyError::CantPay as i8),
+				_ => panic!("sender with no funds should be rejected as Invalid(CantPay)"),
+			}
+		});
+	}
+
+	#[test]
+	fn runtime_upgrade_hook_fires_once_on_version_change() {
+		TEST_SPEC_VERSION.with(|v| v.set(1));
+		MIGRATIONS_RUN.with(|m| m.set(0));
+
+		let t = system::GenesisConfig::<Runtime>::default().build_storage().unwrap();
+		let mut t = runtime_io::TestExternalities::from(t);
+		with_externalities(&mut t, || {
+			// First block ever: there's no recorded version yet, so the hook fires once.
+			ExecutiveWithMigration::initialise_block(
+				&Header::new(1, H256::default(), H256::default(), [69u8; 32].into(), Digest::default())
+			);
+			assert_eq!(MIGRATIONS_RUN.with(|m| m.get()), 1);
+
+			// Same spec_version again: no upgrade, hook must not fire again.
+			ExecutiveWithMigration::initialise_block(
+				&Header::new(2, H256::default(), H256::default(), [69u8; 32].into(), Digest::default())
+			);
+			assert_eq!(MIGRATIONS_RUN.with(|m| m.get()), 1);
+
+			// Bump the compiled-in spec_version: the hook should fire exactly once more.
+			TEST_SPEC_VERSION.with(|v| v.set(2));
+			ExecutiveWithMigration::initialise_block(
+				&Header::new(3, H256::default(), H256::default(), [69u8; 32].into(), Digest::default())
+			);
+			assert_eq!(MIGRATIONS_RUN.with(|m| m.get()), 2);
+
+			// Unchanged again at the new version: still no further firing.
+			ExecutiveWithMigration::initialise_block(
+				&Header::new(4, H256::default(), H256::default(), [69u8; 32].into(), Digest::default())
+			);
+			assert_eq!(MIGRATIONS_RUN.with(|m| m.get()), 2);
+		});
+	}
+
+	#[test]
+	fn on_initialise_runs_after_runtime_upgrade_check() {
+		TEST_SPEC_VERSION.with(|v| v.set(1));
+		MIGRATIONS_RUN.with(|m| m.set(0));
+		HOOK_ORDER.with(|o| o.borrow_mut().clear());
+
+		let t = system::GenesisConfig::<Runtime>::default().build_storage().unwrap();
+		let mut t = runtime_io::TestExternalities::from(t);
+		with_externalities(&mut t, || {
+			// First block: there's no recorded version yet, so the migration runs, and it must
+			// run before `on_initialise` sees the block.
+			ExecutiveWithOrderLog::initialise_block(
+				&Header::new(1, H256::default(), H256::default(), [69u8; 32].into(), Digest::default())
+			);
+			assert_eq!(HOOK_ORDER.with(|o| o.borrow().clone()), vec!["migration", "on_initialise"]);
+
+			// Second block, no version change: no migration, but `on_initialise` still runs.
+			HOOK_ORDER.with(|o| o.borrow_mut().clear());
+			ExecutiveWithOrderLog::initialise_block(
+				&Header::new(2, H256::default(), H256::default(), [69u8; 32].into(), Digest::default())
+			);
+			assert_eq!(HOOK_ORDER.with(|o| o.borrow().clone()), vec!["on_initialise"]);
+		});
+	}
+
+	#[test]
+	fn offchain_worker_reestablishes_context_without_applying_extrinsics() {
+		HOOK_ORDER.with(|o| o.borrow_mut().clear());
+		OFFCHAIN_WORKER_BLOCK.with(|b| b.set(0));
+
+		let mut t = new_funded_test_ext();
+		with_externalities(&mut t, || {
+			ExecutiveWithOffchainWorker::offchain_worker(
+				&Header::new(1, H256::default(), H256::default(), [69u8; 32].into(), Digest::default())
+			);
+
+			// The same block context is established as `initialise_block` would (`on_initialise`
+			// runs), then the off-chain hook fires for that block number.
+			assert_eq!(HOOK_ORDER.with(|o| o.borrow().clone()), vec!["on_initialise", "offchain"]);
+			assert_eq!(OFFCHAIN_WORKER_BLOCK.with(|b| b.get()), 1);
+
+			// No extrinsics were ever handed to `offchain_worker` (there's no way to), so no
+			// dispatch happens and balances are untouched.
+			assert_eq!(<balances::Module<Runtime>>::total_balance(&1), 111);
+		});
+	}
+
 	fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
 		let mut t = system::GenesisConfig::<Runtime>::default().build_storage().unwrap();
 		t.extend(balances::GenesisConfig::<Runtime>::default().build_storage().unwrap());